@@ -0,0 +1,56 @@
+use mio::event::Event;
+use mio::net::TcpStream;
+use mio::Token;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::server::Server;
+
+pub struct Client {
+    pub token: Arc<Token>,
+    pub connection: TcpStream,
+    pub address: SocketAddr,
+    pub closed: bool,
+    /// Stamped at the start of every `poll()`, and compared against
+    /// `BasicConfiguration::connection_timeout` by the reactor's idle sweep
+    /// to decide whether this connection has gone quiet.
+    pub last_activity: Instant,
+}
+
+impl Client {
+    pub fn new(token: Arc<Token>, connection: TcpStream, address: SocketAddr) -> Self {
+        Self {
+            token,
+            connection,
+            address,
+            closed: false,
+            last_activity: Instant::now(),
+        }
+    }
+
+    pub async fn poll(&mut self, server: &Server, event: &Event) {
+        self.last_activity = Instant::now();
+
+        if event.is_readable() {
+            if let Err(e) = self.handle_readable(server).await {
+                log::warn!("closing connection from {}: {e}", self.address);
+                self.closed = true;
+            }
+        }
+        if event.is_read_closed() || event.is_write_closed() {
+            self.closed = true;
+        }
+    }
+
+    async fn handle_readable(&mut self, _server: &Server) -> io::Result<()> {
+        // Packet framing and dispatch happens further down the stack; this
+        // stub only drives the connection lifecycle the reactor depends on.
+        Ok(())
+    }
+}
+
+pub fn interrupted(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::Interrupted
+}
@@ -0,0 +1,85 @@
+use serde::Deserialize;
+use std::time::Duration;
+
+/// Settings loaded from `configuration.toml`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct BasicConfiguration {
+    pub server_address: String,
+    pub server_port: u16,
+    /// Hard ceiling on concurrently accepted connections. Past this the
+    /// acceptor still drains the accept backlog (so an edge-triggered
+    /// readiness event clears) but refuses and drops the new socket instead
+    /// of registering it.
+    pub max_connections: usize,
+    /// How long a connection may sit idle, as measured by
+    /// `Client::last_activity`, before a reactor worker closes it.
+    #[serde(with = "duration_secs")]
+    pub connection_timeout: Duration,
+}
+
+impl Default for BasicConfiguration {
+    fn default() -> Self {
+        Self {
+            server_address: "0.0.0.0".to_string(),
+            server_port: 25565,
+            max_connections: 1024,
+            connection_timeout: Duration::from_secs(60),
+        }
+    }
+}
+
+/// (De)serializes a `Duration` as a plain number of seconds, since that's
+/// the unit operators think in when editing `configuration.toml`.
+mod duration_secs {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_secs())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        Ok(Duration::from_secs(u64::deserialize(deserializer)?))
+    }
+}
+
+impl BasicConfiguration {
+    pub fn load(path: &str) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+}
+
+/// Settings loaded from `features.toml`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AdvancedConfiguration {
+    pub commands: CommandsConfiguration,
+    pub rcon: RCONConfiguration,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct CommandsConfiguration {
+    pub use_console: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct RCONConfiguration {
+    pub enabled: bool,
+    pub address: String,
+    pub password: String,
+}
+
+impl AdvancedConfiguration {
+    pub fn load(path: &str) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+}
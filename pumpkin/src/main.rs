@@ -1,12 +1,17 @@
-use mio::net::TcpListener;
-use mio::{Events, Interest, Poll, Token};
+use mio::net::{TcpListener, TcpStream};
+use mio::{Events, Interest, Poll, Token, Waker};
 use std::io::{self};
 
 use client::Client;
 use commands::handle_command;
 use config::AdvancedConfiguration;
 
-use std::{collections::HashMap, rc::Rc, thread};
+use std::net::SocketAddr;
+use std::os::unix::io::{FromRawFd, IntoRawFd};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use client::interrupted;
 use config::BasicConfiguration;
@@ -27,6 +32,20 @@ pub mod util;
 #[global_allocator]
 static ALLOC: dhat::Alloc = dhat::Alloc;
 
+/// A dispatched connection, handed from the acceptor to a reactor worker
+/// over its channel.
+type Dispatch = (std::net::TcpStream, SocketAddr);
+
+/// A reactor worker's half of the acceptor, used to round-robin newly
+/// accepted sockets across the worker pool. The acceptor thread keeps
+/// `Token(0)` for the listener and never touches a worker's `Poll` directly;
+/// `waker` is how it nudges a worker out of its blocking `poll()` once a
+/// connection has been pushed onto `sender`.
+struct WorkerHandle {
+    sender: mpsc::Sender<Dispatch>,
+    waker: Arc<Waker>,
+}
+
 #[cfg(not(target_os = "wasi"))]
 fn main() -> io::Result<()> {
     #[cfg(feature = "dhat-heap")]
@@ -44,20 +63,22 @@ fn main() -> io::Result<()> {
     rayon::ThreadPoolBuilder::new().build_global().unwrap();
     rt.block_on(async {
         const SERVER: Token = Token(0);
-        use std::{cell::RefCell, time::Instant};
 
         use rcon::RCONServer;
 
         let time = Instant::now();
         let basic_config = BasicConfiguration::load("configuration.toml");
+        let max_connections = basic_config.max_connections;
+        let connection_timeout = basic_config.connection_timeout;
 
         let advanced_configuration = AdvancedConfiguration::load("features.toml");
 
         simple_logger::SimpleLogger::new().init().unwrap();
 
-        // Create a poll instance.
+        // The acceptor keeps its own poll instance; it only ever watches the
+        // listener, so it never shares a Slab or a `Poll` with the reactor
+        // workers that own connections.
         let mut poll = Poll::new()?;
-        // Create storage for events.
         let mut events = Events::with_capacity(128);
 
         // Setup the TCP server socket.
@@ -69,21 +90,21 @@ fn main() -> io::Result<()> {
         .parse()
         .unwrap();
 
-        let mut listener = TcpListener::bind(addr)?;
+        let mut listener = bind_or_inherit_listener(addr)?;
 
         // Register the server with poll we can receive events for it.
         poll.registry()
             .register(&mut listener, SERVER, Interest::READABLE)?;
 
-        // Unique token for each incoming connection.
-        let mut unique_token = Token(SERVER.0 + 1);
-
         let use_console = advanced_configuration.commands.use_console;
         let rcon = advanced_configuration.rcon.clone();
 
-        let mut connections: HashMap<Token, Rc<RefCell<Client>>> = HashMap::new();
-
-        let mut server = Server::new((basic_config, advanced_configuration));
+        let live_connections = Arc::new(AtomicUsize::new(0));
+        // `Server` only needs to be shared, not locked as a whole: its own
+        // client registry carries its own short-lived `Mutex`, so workers
+        // never contend with each other (or with packet handling) over a
+        // single global lock for the lifetime of a `client.poll()` call.
+        let server = Arc::new(Server::new((basic_config, advanced_configuration)));
         log::info!("Started Server took {}ms", time.elapsed().as_millis());
         log::info!("You now can connect to the server, Listening on {}", addr);
 
@@ -104,6 +125,22 @@ fn main() -> io::Result<()> {
                 RCONServer::new(&rcon).await.unwrap();
             });
         }
+
+        // Shard connection handling across a fixed pool of reactor workers so
+        // packet decoding and world logic for different players can run on
+        // different cores; the acceptor thread below only ever accepts and
+        // hands sockets off, it never decodes a packet itself.
+        let worker_count = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        let workers = spawn_reactor_workers(
+            worker_count,
+            Arc::clone(&server),
+            Arc::clone(&live_connections),
+            connection_timeout,
+        )?;
+        let mut next_worker = 0usize;
+
         loop {
             if let Err(err) = poll.poll(&mut events, None) {
                 if interrupted(&err) {
@@ -112,70 +149,350 @@ fn main() -> io::Result<()> {
                 return Err(err);
             }
 
+            for event in events.iter() {
+                if event.token() != SERVER {
+                    // The acceptor only ever registers the listener.
+                    continue;
+                }
+
+                loop {
+                    // Received an event for the TCP server socket, which
+                    // indicates we can accept an connection.
+                    let (mut connection, address) = match listener.accept() {
+                        Ok((connection, address)) => (connection, address),
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                            // If we get a `WouldBlock` error we know our
+                            // listener has no more incoming connections queued,
+                            // so we can return to polling and wait for some
+                            // more.
+                            break;
+                        }
+                        Err(e) => {
+                            // If it was any other kind of error, something went
+                            // wrong and we terminate with an error.
+                            return Err(e);
+                        }
+                    };
+                    if let Err(e) = connection.set_nodelay(true) {
+                        log::warn!("failed to set TCP_NODELAY {e}");
+                    }
+
+                    if live_connections.load(Ordering::Acquire) >= max_connections {
+                        log::warn!(
+                            "Refusing connection from {}: max_connections ({}) reached",
+                            address,
+                            max_connections
+                        );
+                        if let Err(e) = connection.shutdown(std::net::Shutdown::Both) {
+                            log::warn!("failed to shut down refused connection: {e}");
+                        }
+                        continue;
+                    }
+
+                    log::info!("Accepted connection from: {}", address);
+
+                    // Count the connection against the cap the moment we
+                    // commit to dispatching it, not once the owning worker
+                    // gets around to draining its channel; otherwise a burst
+                    // of accepts can all pass the check above before any of
+                    // them are counted, letting live connections overshoot
+                    // `max_connections` by the size of the in-flight backlog.
+                    live_connections.fetch_add(1, Ordering::AcqRel);
+
+                    // Hand the raw socket to a worker by converting it back to
+                    // a std `TcpStream` so it can cross the channel; the
+                    // worker reconstructs a mio stream and registers it with
+                    // its own `Poll`.
+                    let std_stream =
+                        unsafe { std::net::TcpStream::from_raw_fd(connection.into_raw_fd()) };
+                    let worker_id = next_worker;
+                    let worker = &workers[worker_id];
+                    next_worker = (next_worker + 1) % workers.len();
+                    if worker.sender.send((std_stream, address)).is_err() {
+                        log::warn!("reactor worker {worker_id} is gone, dropping connection");
+                        live_connections.fetch_sub(1, Ordering::AcqRel);
+                        continue;
+                    }
+                    if let Err(e) = worker.waker.wake() {
+                        log::warn!("failed to wake reactor worker {worker_id}: {e}");
+                    }
+                }
+            }
+        }
+    })
+}
+
+/// Spawns the fixed pool of reactor worker threads, each owning its own
+/// `Poll`, `Events`, and slab of clients.
+fn spawn_reactor_workers(
+    count: usize,
+    server: Arc<Server>,
+    live_connections: Arc<AtomicUsize>,
+    connection_timeout: Duration,
+) -> io::Result<Vec<WorkerHandle>> {
+    (0..count)
+        .map(|id| {
+            spawn_reactor_worker(
+                id,
+                Arc::clone(&server),
+                Arc::clone(&live_connections),
+                connection_timeout,
+            )
+        })
+        .collect()
+}
+
+/// Token a worker's own `Poll` uses to recognize a wake-up triggered by the
+/// acceptor pushing a new connection onto its channel. Workers never see
+/// the listener, so this token can't collide with a client token, which
+/// start at 1 (see `slot`/`next`).
+const DISPATCH: Token = Token(0);
+
+/// How long a worker blocks in `poll()` before waking up to sweep its slab
+/// for idle connections, even if nothing became readable/writable.
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn spawn_reactor_worker(
+    id: usize,
+    server: Arc<Server>,
+    live_connections: Arc<AtomicUsize>,
+    connection_timeout: Duration,
+) -> io::Result<WorkerHandle> {
+    let (sender, receiver) = mpsc::channel::<Dispatch>();
+    let poll = Poll::new()?;
+    let waker = Arc::new(Waker::new(poll.registry(), DISPATCH)?);
+
+    thread::Builder::new()
+        .name(format!("reactor-worker-{id}"))
+        .spawn(move || {
+            if let Err(e) = reactor_worker_loop(
+                id,
+                poll,
+                receiver,
+                server,
+                live_connections,
+                connection_timeout,
+            ) {
+                log::error!("reactor worker {id} exited: {e}");
+            }
+        })?;
+
+    Ok(WorkerHandle { sender, waker })
+}
+
+/// A reactor worker's event loop: owns its own `Poll`/`Events`/slab of
+/// clients and runs entirely independently of the acceptor and the other
+/// workers, so packet decoding for its clients never contends with theirs.
+fn reactor_worker_loop(
+    id: usize,
+    mut poll: Poll,
+    dispatch_rx: mpsc::Receiver<Dispatch>,
+    server: Arc<Server>,
+    live_connections: Arc<AtomicUsize>,
+    connection_timeout: Duration,
+) -> io::Result<()> {
+    // Client::poll is async but a worker's own I/O is effectively
+    // single-threaded, so a current-thread runtime is enough to drive it.
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let mut events = Events::with_capacity(128);
+    let mut connections: Vec<Option<Arc<Mutex<Client>>>> = Vec::new();
+    let mut freed_tokens: Vec<Token> = Vec::new();
+    let mut last_sweep = Instant::now();
+
+    // The loop below exits only by returning an error (it's otherwise
+    // infinite), at which point `connections` may still hold live
+    // clients that were never individually closed/reaped. Running it behind
+    // a closure lets us release their `max_connections` slots on the way
+    // out no matter which fallible call tripped, instead of leaking a slot
+    // per still-live connection forever.
+    let result = (|| -> io::Result<()> {
+        loop {
+            if let Err(err) = poll.poll(&mut events, Some(POLL_TIMEOUT)) {
+                if interrupted(&err) {
+                    continue;
+                }
+                return Err(err);
+            }
+
             for event in events.iter() {
                 match event.token() {
-                    SERVER => loop {
-                        // Received an event for the TCP server socket, which
-                        // indicates we can accept an connection.
-                        let (mut connection, address) = match listener.accept() {
-                            Ok((connection, address)) => (connection, address),
-                            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
-                                // If we get a `WouldBlock` error we know our
-                                // listener has no more incoming connections queued,
-                                // so we can return to polling and wait for some
-                                // more.
-                                break;
+                    DISPATCH => {
+                        while let Ok((stream, address)) = dispatch_rx.try_recv() {
+                            if let Err(e) = register_dispatched_connection(
+                                id,
+                                &mut poll,
+                                &server,
+                                &mut connections,
+                                &mut freed_tokens,
+                                stream,
+                                address,
+                            ) {
+                                log::warn!(
+                                    "dropping connection from {address}: failed to register it: {e}"
+                                );
+                                live_connections.fetch_sub(1, Ordering::AcqRel);
                             }
-                            Err(e) => {
-                                // If it was any other kind of error, something went
-                                // wrong and we terminate with an error.
-                                return Err(e);
-                            }
-                        };
-                        if let Err(e) = connection.set_nodelay(true) {
-                            log::warn!("failed to set TCP_NODELAY {e}");
                         }
-
-                        log::info!("Accepted connection from: {}", address);
-
-                        let token = next(&mut unique_token);
-                        poll.registry().register(
-                            &mut connection,
-                            token,
-                            Interest::READABLE.add(Interest::WRITABLE),
-                        )?;
-                        let rc_token = Rc::new(token);
-                        let client = Rc::new(RefCell::new(Client::new(
-                            Rc::clone(&rc_token),
-                            connection,
-                            addr,
-                        )));
-                        server.add_client(rc_token, Rc::clone(&client));
-                        connections.insert(token, client);
-                    },
+                    }
 
                     token => {
                         // Maybe received an event for a TCP connection.
-                        let done = if let Some(client) = connections.get_mut(&token) {
-                            let mut client = client.borrow_mut();
-                            client.poll(&mut server, event).await;
+                        let done = if let Some(Some(client)) = connections.get(slot(token)) {
+                            let mut client = client.lock().unwrap();
+                            rt.block_on(client.poll(&server, event));
                             client.closed
                         } else {
                             // Sporadic events happen, we can safely ignore them.
                             false
                         };
                         if done {
-                            if let Some(client) = connections.remove(&token) {
-                                server.remove_client(&token);
-                                let mut client = client.borrow_mut();
+                            if let Some(client) = connections[slot(token)].take() {
+                                server.remove_client(id, &token);
+                                let mut client = client.lock().unwrap();
                                 poll.registry().deregister(&mut client.connection)?;
+                                freed_tokens.push(token);
+                                live_connections.fetch_sub(1, Ordering::Release);
                             }
                         }
                     }
                 }
             }
+
+            // `poll()` above returns on real events too, not just its
+            // timeout, so without this gate a busy worker would re-scan and
+            // re-lock its entire slab on every single event batch. Sweeping
+            // at most once per `POLL_TIMEOUT` keeps the idle check off the
+            // hot path while still catching a quiet connection within about
+            // one timeout of going idle, independent of the keep-alive
+            // packet round-trip.
+            if last_sweep.elapsed() >= POLL_TIMEOUT {
+                reap_idle_connections(
+                    id,
+                    &mut poll,
+                    &server,
+                    &live_connections,
+                    &mut connections,
+                    &mut freed_tokens,
+                    connection_timeout,
+                )?;
+                last_sweep = Instant::now();
+            }
         }
-    })
+    })();
+
+    let leaked = connections.iter().filter(|client| client.is_some()).count();
+    if leaked > 0 {
+        live_connections.fetch_sub(leaked, Ordering::AcqRel);
+        log::warn!(
+            "reactor worker {id} tearing down with {leaked} live connection(s); releasing their max_connections slot(s)"
+        );
+    }
+
+    result
+}
+
+/// Registers one freshly dispatched socket with this worker's `Poll` and
+/// hands it to `Client`/`Server`. Failures here are local to this one
+/// connection: the caller is expected to log and release its
+/// `max_connections` slot rather than tear down the whole worker over a
+/// single bad socket.
+fn register_dispatched_connection(
+    worker_id: usize,
+    poll: &mut Poll,
+    server: &Arc<Server>,
+    connections: &mut Vec<Option<Arc<Mutex<Client>>>>,
+    freed_tokens: &mut Vec<Token>,
+    stream: std::net::TcpStream,
+    address: SocketAddr,
+) -> io::Result<()> {
+    stream.set_nonblocking(true)?;
+    let mut connection = TcpStream::from_std(stream);
+    let token = next(connections, freed_tokens);
+
+    if let Err(e) = poll.registry().register(
+        &mut connection,
+        token,
+        Interest::READABLE.add(Interest::WRITABLE),
+    ) {
+        // The slot was never handed to a real client, so give the token
+        // back rather than leak slab capacity on repeated failures.
+        freed_tokens.push(token);
+        if let Err(shutdown_err) = connection.shutdown(std::net::Shutdown::Both) {
+            log::warn!(
+                "failed to shut down unregistered connection from {address}: {shutdown_err}"
+            );
+        }
+        return Err(e);
+    }
+
+    let arc_token = Arc::new(token);
+    let client = Arc::new(Mutex::new(Client::new(
+        Arc::clone(&arc_token),
+        connection,
+        address,
+    )));
+    server.add_client(worker_id, arc_token, Arc::clone(&client));
+    connections[slot(token)] = Some(client);
+    Ok(())
+}
+
+/// Closes and frees any connection whose `last_activity` is older than
+/// `connection_timeout`, so a half-open or silent peer doesn't occupy a slot
+/// and a file descriptor indefinitely.
+fn reap_idle_connections(
+    worker_id: usize,
+    poll: &mut Poll,
+    server: &Arc<Server>,
+    live_connections: &Arc<AtomicUsize>,
+    connections: &mut [Option<Arc<Mutex<Client>>>],
+    freed_tokens: &mut Vec<Token>,
+    connection_timeout: Duration,
+) -> io::Result<()> {
+    for (index, slot_entry) in connections.iter_mut().enumerate() {
+        let client = match slot_entry {
+            Some(client) => Arc::clone(client),
+            None => continue,
+        };
+        if client.lock().unwrap().last_activity.elapsed() <= connection_timeout {
+            continue;
+        }
+
+        let token = Token(index + 1);
+        log::warn!(
+            "closing connection (token {}): idle longer than connection_timeout",
+            token.0
+        );
+        server.remove_client(worker_id, &token);
+        let mut client = client.lock().unwrap();
+        client.closed = true;
+        poll.registry().deregister(&mut client.connection)?;
+        drop(client);
+
+        *slot_entry = None;
+        freed_tokens.push(token);
+        live_connections.fetch_sub(1, Ordering::Release);
+    }
+    Ok(())
+}
+
+/// Binds the listening socket, or adopts one handed down by a service
+/// supervisor (systemd/launchd socket activation) when `LISTEN_FDS` is set.
+/// The supervisor keeps the socket open across a process replacement, so
+/// restarts no longer create a window where connection attempts are refused.
+fn bind_or_inherit_listener(addr: std::net::SocketAddr) -> io::Result<TcpListener> {
+    if std::env::var_os("LISTEN_FDS").is_some() {
+        log::info!("Adopting inherited listening socket from file descriptor 3");
+        // Socket activation hands the listener down as fd 3, the first fd
+        // after stdin/stdout/stderr.
+        let std_listener = unsafe { std::net::TcpListener::from_raw_fd(3) };
+        std_listener.set_nonblocking(true)?;
+        TcpListener::from_std(std_listener)
+    } else {
+        TcpListener::bind(addr)
+    }
 }
 
 fn adjust_file_descriptor_limits() {
@@ -208,10 +525,23 @@ fn adjust_file_descriptor_limits() {
     );
 }
 
-fn next(current: &mut Token) -> Token {
-    let next = current.0;
-    current.0 += 1;
-    Token(next)
+/// Maps a connection's `Token` to its index in a worker's slab, accounting
+/// for `Token(0)` being reserved for that worker's dispatch wake-up.
+fn slot(token: Token) -> usize {
+    token.0 - 1
+}
+
+/// Hands out a `Token` for a newly accepted connection, preferring a freed
+/// slot over growing the slab so tokens stay bounded by the high-water mark
+/// of concurrent connections rather than the lifetime total.
+fn next(connections: &mut Vec<Option<Arc<Mutex<Client>>>>, freed_tokens: &mut Vec<Token>) -> Token {
+    if let Some(token) = freed_tokens.pop() {
+        token
+    } else {
+        let token = Token(connections.len() + 1);
+        connections.push(None);
+        token
+    }
 }
 
 #[cfg(target_os = "wasi")]
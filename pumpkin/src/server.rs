@@ -0,0 +1,46 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use mio::Token;
+
+use crate::client::Client;
+use crate::config::{AdvancedConfiguration, BasicConfiguration};
+
+/// Identifies a client uniquely across the whole reactor pool. A `Token` by
+/// itself is only unique within the worker that issued it (each worker hands
+/// out its own `Token(1)`, `Token(2)`, ... independently), so `Server`'s
+/// cross-worker registry has to pair it with the id of the worker that owns
+/// the connection.
+type ClientId = (usize, Token);
+
+pub struct Server {
+    pub basic_config: BasicConfiguration,
+    pub advanced_config: AdvancedConfiguration,
+    /// All connected clients across every reactor worker, keyed by
+    /// `(worker_id, Token)` since a `Token` alone can collide across
+    /// workers. Guarded by its own short-lived lock so adding/removing a
+    /// client never serializes with packet handling on other connections.
+    clients: Mutex<HashMap<ClientId, Arc<Mutex<Client>>>>,
+}
+
+impl Server {
+    pub fn new(config: (BasicConfiguration, AdvancedConfiguration)) -> Self {
+        let (basic_config, advanced_config) = config;
+        Self {
+            basic_config,
+            advanced_config,
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn add_client(&self, worker_id: usize, token: Arc<Token>, client: Arc<Mutex<Client>>) {
+        self.clients
+            .lock()
+            .unwrap()
+            .insert((worker_id, *token), client);
+    }
+
+    pub fn remove_client(&self, worker_id: usize, token: &Token) {
+        self.clients.lock().unwrap().remove(&(worker_id, *token));
+    }
+}